@@ -4,14 +4,154 @@ use std::collections::HashMap;
 use std::env;
 use std::fmt;
 use std::process;
+use std::str::FromStr;
 
 use getopts::HasArg;
 use getopts::Occur;
 use getopts::Options as GetOptOptions;
 
+/// Terminal width assumed for help output when `$COLUMNS` isn't set.
+const DEFAULT_TERMINAL_WIDTH: usize = 80;
+/// Width, in characters, of the option/argument name column in help output.
+const NAME_COLUMN_WIDTH: usize = 24;
+/// Narrowest the description column is ever wrapped to, even on a very
+/// narrow terminal.
+const MIN_DESCRIPTION_WIDTH: usize = 20;
+
 pub enum OptAction {
     StoreTrue,
     StoreFalse,
+    /// Counts how many times the option was given (e.g. `-v`, `-vv`,
+    /// `-vvv`), stored as the decimal string of the occurrence count.
+    StoreCount,
+}
+
+/// Expected value type for an option.
+///
+/// When set via [`OptionParser::add_option`], the parser validates that
+/// every value given for the option actually converts to this type,
+/// rather than letting a bad value surface later at [`Options::get_as`].
+pub enum ValueType {
+    Str,
+    Int,
+    Float,
+    Bool,
+}
+impl ValueType {
+    fn validate(&self, value: &str) -> Result<(), String> {
+        match self {
+            ValueType::Str => Ok(()),
+            ValueType::Int => value.parse::<i64>().map(|_| ()).map_err(|e| e.to_string()),
+            ValueType::Float => value.parse::<f64>().map(|_| ()).map_err(|e| e.to_string()),
+            ValueType::Bool => value.parse::<bool>().map(|_| ()).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Where a parsed option's value actually came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueSource {
+    /// The user gave the option (or flag) on the command line.
+    CommandLine,
+    /// The option was absent, but its backing environment variable
+    /// (`env` in [`OptionParser::add_option`]) was set.
+    Environment,
+    /// The option was absent and had no environment variable set, so
+    /// its hard-coded `default` was used.
+    Default,
+}
+
+/// Errors produced while reading back a parsed value (`Options::get_as`,
+/// `Options::get_all_as`) or while parsing arguments themselves
+/// (`OptionParser::try_parse`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// A required option or positional argument was not given and has
+    /// no default.
+    MissingRequired(String),
+    /// An option's given value(s) don't match how the option was
+    /// declared (e.g. a flag option combined with `multiple`, or a
+    /// non-flag option present without a value).
+    UnexpectedFlagValue(String),
+    /// The stored value for `option` could not be parsed into the
+    /// requested type; `value` is the offending string and `message`
+    /// is the underlying conversion error.
+    ConversionFailed {
+        option: String,
+        value: String,
+        message: String,
+    },
+    /// More positional arguments were given than were declared via
+    /// `add_argument`.
+    UnexpectedPositionalArgument(Vec<String>),
+    /// The given value for `option` is not one of its declared
+    /// `choices`.
+    InvalidChoice {
+        option: String,
+        value: String,
+        choices: Vec<String>,
+    },
+    /// `getopts` itself rejected the argument list, e.g. an unknown
+    /// option or a missing value for it.
+    GetoptsFailure(String),
+    /// `-h`/`--help` was given; the caller should show usage help and
+    /// exit successfully rather than treat this as a failure.
+    HelpRequested,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::MissingRequired(name) => {
+                write!(fmt, "{} is required but was not given.", name)
+            }
+            ParseError::UnexpectedFlagValue(name) => {
+                write!(fmt, "{} has an unexpected value configuration.", name)
+            }
+            ParseError::ConversionFailed {
+                option,
+                value,
+                message,
+            } => write!(
+                fmt,
+                "option `{}` has value {:?} which is not valid: {}",
+                option, value, message
+            ),
+            ParseError::UnexpectedPositionalArgument(extra) => {
+                write!(fmt, "too many positional arguments given: {:?}", extra)
+            }
+            ParseError::InvalidChoice {
+                option,
+                value,
+                choices,
+            } => write!(
+                fmt,
+                "option `{}` has value {:?} which is not one of the valid choices: {:?}",
+                option, value, choices
+            ),
+            ParseError::GetoptsFailure(message) => write!(fmt, "{}", message),
+            ParseError::HelpRequested => write!(fmt, "help was requested"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+struct ArgumentDef {
+    name: String,
+    required: bool,
+    multiple: bool,
+    help: String,
+}
+impl ArgumentDef {
+    fn new() -> Self {
+        Self {
+            name: String::from(""),
+            required: false,
+            multiple: false,
+            help: String::from(""),
+        }
+    }
 }
 
 struct OptionDef {
@@ -23,6 +163,9 @@ struct OptionDef {
     action: Option<OptAction>,
     help: String,
     uppercase_name: String,
+    expected_type: Option<ValueType>,
+    choices: Option<Vec<String>>,
+    env: Option<String>,
 }
 impl OptionDef {
     fn new() -> Self {
@@ -35,6 +178,9 @@ impl OptionDef {
             action: None,
             help: String::from(""),
             uppercase_name: String::from(""),
+            expected_type: None,
+            choices: None,
+            env: None,
         }
     }
 }
@@ -42,6 +188,15 @@ impl OptionDef {
 pub struct Options {
     defined_names: Vec<String>,
     parsed_options: HashMap<String, Vec<String>>,
+    positional_options: HashMap<String, Vec<String>>,
+    free: Vec<String>,
+    value_sources: HashMap<String, ValueSource>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Options {
@@ -49,6 +204,9 @@ impl Options {
         Self {
             defined_names: Vec::new(),
             parsed_options: HashMap::new(),
+            positional_options: HashMap::new(),
+            free: Vec::new(),
+            value_sources: HashMap::new(),
         }
     }
 
@@ -59,9 +217,48 @@ impl Options {
     }
 
     pub fn insert(&mut self, key: String, values: Vec<String>) -> Option<Vec<String>> {
+        self.value_sources
+            .insert(key.clone(), ValueSource::CommandLine);
+        self.parsed_options.insert(key, values)
+    }
+
+    /// Like [`Options::insert`], but records where the value came from
+    /// rather than assuming it was given on the command line.
+    pub fn insert_with_source(
+        &mut self,
+        key: String,
+        values: Vec<String>,
+        source: ValueSource,
+    ) -> Option<Vec<String>> {
+        self.value_sources.insert(key.clone(), source);
         self.parsed_options.insert(key, values)
     }
 
+    /// Returns where `key`'s stored value came from: the command line,
+    /// an environment variable, or the option's default.
+    pub fn value_source(&self, key: &String) -> Option<&ValueSource> {
+        self.value_sources.get(key)
+    }
+
+    pub fn insert_positional(&mut self, key: String, values: Vec<String>) -> Option<Vec<String>> {
+        self.positional_options.insert(key, values)
+    }
+
+    pub fn positional(&self, key: &String) -> Option<&Vec<String>> {
+        self.positional_options.get(key)
+    }
+
+    pub fn set_free(&mut self, free: Vec<String>) {
+        self.free = free;
+    }
+
+    /// Returns every non-option argument given on the command line, in
+    /// order, regardless of whether it was bound to a declared
+    /// positional name.
+    pub fn free(&self) -> &Vec<String> {
+        &self.free
+    }
+
     pub fn get(&self, key: &String) -> Option<&Vec<String>> {
         if self.parsed_options.contains_key(key) {
             self.parsed_options.get(key)
@@ -70,16 +267,57 @@ impl Options {
         }
     }
 
-    pub fn contains_key(&self, key: &String) -> bool {
-        if self.parsed_options.contains_key(key) {
-            true
-        } else if self.defined_names.contains(key) {
-            true
-        } else {
-            false
+    /// Converts the single stored value for `key` to `T`, if present.
+    ///
+    /// Returns `Ok(None)` when the option was not given and has no
+    /// stored value, and `Err(ParseError::ConversionFailed)` when the
+    /// stored value does not parse as `T`.
+    pub fn get_as<T: FromStr>(&self, key: &String) -> Result<Option<T>, ParseError>
+    where
+        T::Err: fmt::Display,
+    {
+        match self.get(key).and_then(|values| values.first()) {
+            Some(v) => v
+                .parse::<T>()
+                .map(Some)
+                .map_err(|e| ParseError::ConversionFailed {
+                    option: key.clone(),
+                    value: v.clone(),
+                    message: e.to_string(),
+                }),
+            None => Ok(None),
         }
     }
 
+    /// Converts every stored value for `key` to `T`, if present.
+    ///
+    /// Like [`Options::get_as`], but for options that accept multiple
+    /// values (`multiple: true` in `add_option`).
+    pub fn get_all_as<T: FromStr>(&self, key: &String) -> Result<Option<Vec<T>>, ParseError>
+    where
+        T::Err: fmt::Display,
+    {
+        match self.get(key) {
+            Some(values) => {
+                let mut converted = Vec::with_capacity(values.len());
+                for v in values {
+                    let parsed = v.parse::<T>().map_err(|e| ParseError::ConversionFailed {
+                        option: key.clone(),
+                        value: v.clone(),
+                        message: e.to_string(),
+                    })?;
+                    converted.push(parsed);
+                }
+                Ok(Some(converted))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn contains_key(&self, key: &String) -> bool {
+        self.parsed_options.contains_key(key) || self.defined_names.contains(key)
+    }
+
     pub fn defined_len(&self) -> usize {
         self.defined_names.len()
     }
@@ -94,6 +332,9 @@ impl fmt::Debug for Options {
         fmt.debug_struct("Options")
             .field("defined_names", &self.defined_names)
             .field("parsed_options", &self.parsed_options)
+            .field("positional_options", &self.positional_options)
+            .field("free", &self.free)
+            .field("value_sources", &self.value_sources)
             .finish()
     }
 }
@@ -101,6 +342,13 @@ impl fmt::Debug for Options {
 pub struct OptionParser {
     opts: GetOptOptions,
     given_options: Vec<OptionDef>,
+    given_arguments: Vec<ArgumentDef>,
+}
+
+impl Default for OptionParser {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl OptionParser {
@@ -108,19 +356,21 @@ impl OptionParser {
         Self {
             opts: GetOptOptions::new(),
             given_options: Vec::new(),
+            given_arguments: Vec::new(),
         }
     }
 
     fn show_usage(&self, program_name: &String) {
         let mut options_for_brief = "".to_string();
         for o in &self.given_options {
+            let placeholder = Self::option_placeholder(o);
             let mut single_opt = format!(
                 "--{}{}",
                 o.name,
                 if o.action.is_some() {
                     "".to_string()
                 } else {
-                    format!(" {}", o.uppercase_name)
+                    format!(" {}", placeholder)
                 }
             );
             if o.multiple {
@@ -132,16 +382,206 @@ impl OptionParser {
             single_opt = format!(" {}", single_opt);
             options_for_brief.push_str(&single_opt);
         }
+        for a in &self.given_arguments {
+            let mut single_arg = a.name.clone();
+            if a.multiple {
+                single_arg = format!("{}...", single_arg);
+            }
+            if !a.required {
+                single_arg = format!("[{}]", single_arg);
+            }
+            single_arg = format!(" {}", single_arg);
+            options_for_brief.push_str(&single_arg);
+        }
         let brief = format!("Usage: {}{}", program_name, options_for_brief);
-        println!("{}", self.opts.usage(&brief));
+
+        let width = Self::terminal_width();
+        let mut output = format!("{}\n\nOptions:\n", brief);
+        for o in &self.given_options {
+            output.push_str(&Self::format_help_row(
+                &Self::option_name_column(o),
+                &Self::option_description(o),
+                width,
+            ));
+            output.push('\n');
+        }
+        if !self.given_options.iter().any(|x| x.name == "help") {
+            output.push_str(&Self::format_help_row(
+                "-h, --help",
+                "show this help message and exit",
+                width,
+            ));
+            output.push('\n');
+        }
+
+        if !self.given_arguments.is_empty() {
+            output.push_str("\nArguments:\n");
+            for a in &self.given_arguments {
+                output.push_str(&Self::format_help_row(
+                    &a.name,
+                    &Self::argument_description(a),
+                    width,
+                ));
+                output.push('\n');
+            }
+        }
+
+        println!("{}", output);
+    }
+
+    /// Returns `$COLUMNS` when it's set to a positive integer, or
+    /// `DEFAULT_TERMINAL_WIDTH` otherwise.
+    fn terminal_width() -> usize {
+        env::var("COLUMNS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|&w| w > 0)
+            .unwrap_or(DEFAULT_TERMINAL_WIDTH)
+    }
+
+    fn option_placeholder(o: &OptionDef) -> String {
+        match &o.choices {
+            Some(choices) => format!("{{{}}}", choices.join(",")),
+            None => o.uppercase_name.clone(),
+        }
+    }
+
+    /// Builds the `-i, --input VALUE` style name column for an option's
+    /// help row.
+    fn option_name_column(o: &OptionDef) -> String {
+        let mut name_column = String::new();
+        if !o.short_name.is_empty() {
+            name_column.push('-');
+            name_column.push_str(&o.short_name);
+            name_column.push_str(", ");
+        }
+        name_column.push_str("--");
+        name_column.push_str(&o.name);
+        if o.action.is_none() {
+            name_column.push(' ');
+            name_column.push_str(&Self::option_placeholder(o));
+        }
+        name_column
+    }
+
+    /// Builds the help description for an option, appending the
+    /// required/default/choices metadata this crate tracks.
+    fn option_description(o: &OptionDef) -> String {
+        let mut notes = Vec::new();
+        if o.required {
+            notes.push("required".to_string());
+        }
+        if let Some(env_name) = &o.env {
+            notes.push(format!("env: {}", env_name));
+        }
+        if let Some(default) = &o.default {
+            notes.push(format!("default: {}", default));
+        }
+        if let Some(choices) = &o.choices {
+            notes.push(format!("choices: {}", choices.join(", ")));
+        }
+        Self::append_notes(&o.help, &notes)
+    }
+
+    /// Builds the help description for a positional argument, appending
+    /// the required/multiple metadata this crate tracks.
+    fn argument_description(a: &ArgumentDef) -> String {
+        let mut notes = Vec::new();
+        if a.required {
+            notes.push("required".to_string());
+        }
+        if a.multiple {
+            notes.push("multiple".to_string());
+        }
+        Self::append_notes(&a.help, &notes)
+    }
+
+    fn append_notes(help: &str, notes: &[String]) -> String {
+        if notes.is_empty() {
+            return help.to_string();
+        }
+        if help.is_empty() {
+            format!("({})", notes.join(", "))
+        } else {
+            format!("{} ({})", help, notes.join(", "))
+        }
+    }
+
+    /// Renders one column-aligned, width-wrapped help row: `name` in the
+    /// first column, `description` word-wrapped into the second column
+    /// at `width`, mirroring getopts' own `OptGroup` usage formatting.
+    fn format_help_row(name: &str, description: &str, width: usize) -> String {
+        let indent = " ".repeat(NAME_COLUMN_WIDTH);
+        let mut row = format!("    {}", name);
+
+        let desc_width = width
+            .saturating_sub(NAME_COLUMN_WIDTH)
+            .max(MIN_DESCRIPTION_WIDTH);
+        let desc_rows = Self::wrap_text(description, desc_width);
+        if desc_rows.is_empty() {
+            return row;
+        }
+
+        if row.len() < NAME_COLUMN_WIDTH {
+            row.push_str(&" ".repeat(NAME_COLUMN_WIDTH - row.len()));
+        } else {
+            row.push('\n');
+            row.push_str(&indent);
+        }
+        row.push_str(&desc_rows.join(&format!("\n{}", indent)));
+        row
+    }
+
+    /// Splits `text` into lines of at most `width` bytes, breaking only
+    /// on whitespace; a single word longer than `width` is kept whole on
+    /// its own line.
+    fn wrap_text(text: &str, width: usize) -> Vec<String> {
+        let width = width.max(1);
+        let mut rows = Vec::new();
+        let mut row = String::new();
+        for word in text.split_whitespace() {
+            if row.is_empty() {
+                row.push_str(word);
+            } else if row.len() + 1 + word.len() <= width {
+                row.push(' ');
+                row.push_str(word);
+            } else {
+                rows.push(row.clone());
+                row.clear();
+                row.push_str(word);
+            }
+        }
+        if !row.is_empty() {
+            rows.push(row);
+        }
+        rows
     }
 
+    /// Parses `env::args()`, printing usage help and then exiting the
+    /// process on `--help`/`-h`, or panicking with the error message on
+    /// any other parse error. Use
+    /// [`OptionParser::try_parse`] instead to handle errors yourself.
     pub fn parse(&mut self) -> Options {
-        let args = env::args().collect();
-        self.parse_with_args(args)
+        let args: Vec<String> = env::args().collect();
+        let program_name = args[0].clone();
+        match self.try_parse(args) {
+            Ok(options) => options,
+            Err(ParseError::HelpRequested) => {
+                self.show_usage(&program_name);
+                process::exit(0);
+            }
+            Err(e) => {
+                self.show_usage(&program_name);
+                panic!("{}", e);
+            }
+        }
     }
 
-    fn parse_with_args(&mut self, args: Vec<String>) -> Options {
+    /// Parses `args` (with `args[0]` being the program name), returning
+    /// the parsed [`Options`] or a [`ParseError`] instead of panicking
+    /// or exiting the process. Callers embedding this crate in a larger
+    /// program can inspect the error and decide how to react.
+    pub fn try_parse(&mut self, args: Vec<String>) -> Result<Options, ParseError> {
         // Always set help option if it's not specified by user.
         if !self.given_options.iter().any(|x| x.name == "help") {
             self.opts.opt(
@@ -157,30 +597,41 @@ impl OptionParser {
         let matches = match self.opts.parse(&args[1..]) {
             Ok(m) => m,
             Err(f) => {
-                self.show_usage(&args[0]);
                 if args[1..].iter().any(|x| x == "--help" || x == "-h") {
                     // If -h or --help exists in args,
-                    // need to show help message and exit
-                    // even though there is any other wrong option.
-                    process::exit(0);
-                } else {
-                    // If simply wrong option exists without -h/--help,
-                    // need to raise an error.
-                    panic!("{}", f.to_string())
+                    // help takes precedence even though there is
+                    // any other wrong option.
+                    return Err(ParseError::HelpRequested);
                 }
+                return Err(ParseError::GetoptsFailure(f.to_string()));
             }
         };
 
         if matches.opt_present("h") {
-            // When an user specifies -h or --help,
+            // When an user specifies -h or --help, the caller should
             // show usage help message and exit.
-            self.show_usage(&args[0]);
-            process::exit(0);
+            return Err(ParseError::HelpRequested);
         }
 
         let mut options = Options::new();
         options.set_defined_names(self.given_options.iter().map(|x| &x.name).collect());
         for o in &self.given_options {
+            if let Some(OptAction::StoreCount) = &o.action {
+                // counting flag: store how many times it was given,
+                // regardless of `multiple`/`required`/`opt_present`.
+                let count = matches.opt_count(&o.name);
+                if count > 0 {
+                    options.insert(o.name.clone(), vec![count.to_string()]);
+                } else if let Some((v, source)) = Self::resolve_fallback_value(o) {
+                    Self::validate_expected_type(o, std::slice::from_ref(&v))?;
+                    Self::validate_choices(o, std::slice::from_ref(&v))?;
+                    options.insert_with_source(o.name.clone(), vec![v], source);
+                } else if o.required {
+                    return Err(ParseError::MissingRequired(o.name.clone()));
+                }
+                continue;
+            }
+
             if matches.opt_present(&o.name) {
                 // option is given.
                 if o.multiple {
@@ -188,31 +639,32 @@ impl OptionParser {
                     if o.action.is_some() {
                         // for an option which accepts multiple values,
                         // this is not allowed to be used as a flag option.
-                        panic!("{} must not be a flag option.", &o.name);
+                        return Err(ParseError::UnexpectedFlagValue(o.name.clone()));
                     }
 
                     let opt_values = matches.opt_strs(&o.name);
-                    if opt_values.len() > 0 {
-                        // if any values are found, directly store them.
+                    if !opt_values.is_empty() {
+                        // if any values are found, validate and store them.
+                        Self::validate_expected_type(o, &opt_values)?;
+                        Self::validate_choices(o, &opt_values)?;
                         options.insert(o.name.clone(), opt_values);
                     } else {
                         // if no value is specified,
                         // check if the option is required or not.
                         if o.required {
-                            panic!("{} is required option.", o.name);
+                            return Err(ParseError::MissingRequired(o.name.clone()));
                         }
                         // But, basically, when the code is reaching here,
                         // given option name and value may be not perfect.
-                        panic!(
-                            "{} must have a value, but only key like --{}",
-                            o.name, o.name
-                        );
+                        return Err(ParseError::UnexpectedFlagValue(o.name.clone()));
                     }
                 } else {
                     // when this option accept only single value.
                     let opt_value = matches.opt_str(&o.name);
                     if let Some(v) = opt_value {
-                        // if a value is found, directly store it.
+                        // if a value is found, validate and store it.
+                        Self::validate_expected_type(o, std::slice::from_ref(&v))?;
+                        Self::validate_choices(o, std::slice::from_ref(&v))?;
                         options.insert(o.name.clone(), vec![v]);
                     } else {
                         // if no value is specified,
@@ -224,31 +676,104 @@ impl OptionParser {
                                 vec![match v {
                                     OptAction::StoreTrue => "true".to_string(),
                                     OptAction::StoreFalse => "false".to_string(),
+                                    OptAction::StoreCount => {
+                                        unreachable!("StoreCount options are handled earlier")
+                                    }
                                 }],
                             );
                         } else {
                             // non flag option.
                             // But, basically, when the code is reaching here,
                             // given option name and value may be not perfect.
-                            panic!(
-                                "{} must have a value, but only key like --{}",
-                                o.name, o.name
-                            );
+                            return Err(ParseError::UnexpectedFlagValue(o.name.clone()));
                         }
                     }
                 }
             } else {
                 // this option is not specified.
-                // need to set default value.
-                if let Some(v) = o.default.clone() {
-                    options.insert(o.name.clone(), vec![v]);
+                // fall back to its environment variable, then its
+                // hard-coded default.
+                if let Some((v, source)) = Self::resolve_fallback_value(o) {
+                    Self::validate_expected_type(o, std::slice::from_ref(&v))?;
+                    Self::validate_choices(o, std::slice::from_ref(&v))?;
+                    options.insert_with_source(o.name.clone(), vec![v], source);
+                } else if o.required {
+                    // `getopts` already enforces this via `Occur::Req`
+                    // when there's no `env` to fall back to; this only
+                    // fires for a required option backed by an unset
+                    // environment variable.
+                    return Err(ParseError::MissingRequired(o.name.clone()));
                 }
             }
         }
 
-        options
+        options.set_free(matches.free.clone());
+        self.bind_positional_arguments(&matches.free, &mut options)?;
+
+        Ok(options)
     }
 
+    fn bind_positional_arguments(
+        &self,
+        free: &[String],
+        options: &mut Options,
+    ) -> Result<(), ParseError> {
+        if self.given_arguments.is_empty() {
+            return Ok(());
+        }
+
+        let mut free_iter = free.iter().cloned();
+        let arg_count = self.given_arguments.len();
+        for (i, a) in self.given_arguments.iter().enumerate() {
+            if i + 1 == arg_count && a.multiple {
+                // the last positional absorbs every remaining free argument.
+                let rest: Vec<String> = free_iter.by_ref().collect();
+                if rest.is_empty() {
+                    if a.required {
+                        return Err(ParseError::MissingRequired(a.name.clone()));
+                    }
+                } else {
+                    options.insert_positional(a.name.clone(), rest);
+                }
+            } else {
+                match free_iter.next() {
+                    Some(v) => {
+                        options.insert_positional(a.name.clone(), vec![v]);
+                    }
+                    None => {
+                        if a.required {
+                            return Err(ParseError::MissingRequired(a.name.clone()));
+                        }
+                    }
+                }
+            }
+        }
+
+        let leftover: Vec<String> = free_iter.collect();
+        if !leftover.is_empty() {
+            return Err(ParseError::UnexpectedPositionalArgument(leftover));
+        }
+
+        Ok(())
+    }
+
+    pub fn add_argument(
+        &mut self,
+        name: &str,
+        required: Option<bool>,
+        multiple: Option<bool>,
+        help: Option<&str>,
+    ) {
+        let mut argument = ArgumentDef::new();
+        argument.name = name.to_string();
+        argument.required = required.unwrap_or(false);
+        argument.multiple = multiple.unwrap_or(false);
+        argument.help = help.map(|v| v.to_string()).unwrap_or_default();
+
+        self.given_arguments.push(argument);
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn add_option(
         &mut self,
         name: &str,
@@ -258,24 +783,26 @@ impl OptionParser {
         default: Option<&str>,
         action: Option<OptAction>,
         help: Option<&str>,
+        expected_type: Option<ValueType>,
+        choices: Option<Vec<String>>,
+        env: Option<&str>,
     ) {
         let mut option = OptionDef::new();
 
         option.name = name.to_string().clone();
         option.short_name = short_name.to_string().clone();
-        option.required = if let Some(v) = required { v } else { false };
-        option.multiple = if let Some(v) = multiple { v } else { false };
-        option.default = if let Some(v) = default {
-            Some(v.to_string().clone())
-        } else {
-            None
-        };
+        option.required = required.unwrap_or(false);
+        option.multiple = multiple.unwrap_or(false);
+        option.default = default.map(|v| v.to_string().clone());
         option.action = action;
         option.help = if let Some(v) = help {
             v.to_string().clone()
         } else {
             "".to_string()
         };
+        option.expected_type = expected_type;
+        option.choices = choices;
+        option.env = env.map(|v| v.to_string());
 
         option.uppercase_name = option.name.to_uppercase();
 
@@ -288,15 +815,26 @@ impl OptionParser {
         } else {
             HasArg::Yes
         };
-        let occur = if option.required {
+        let occur = if matches!(option.action, Some(OptAction::StoreCount)) {
+            // A counting flag must be allowed to repeat (-v, -vv, -vvv, ...).
+            Occur::Multi
+        } else if option.required && option.env.is_none() {
             Occur::Req
-        } else {
+        } else if option.required {
+            // A required option backed by an environment variable must
+            // still be allowed to be absent from the CLI; `try_parse`
+            // enforces `required` manually once the env/default
+            // fallback also comes back empty.
             if option.multiple {
-                // NOTE: a combination of required and multiple will be checked later.
                 Occur::Multi
             } else {
                 Occur::Optional
             }
+        } else if option.multiple {
+            // NOTE: a combination of required and multiple will be checked later.
+            Occur::Multi
+        } else {
+            Occur::Optional
         };
 
         // Set all values.
@@ -312,12 +850,66 @@ impl OptionParser {
         // Add this given option.
         self.given_options.push(option);
     }
+
+    // Checks every value for `o` (given on the command line or taken
+    // from its env/default fallback) against its expected type, if one
+    // was set via `add_option`, returning `ParseError::ConversionFailed`
+    // with the option name and offending value on the first mismatch.
+    fn validate_expected_type(o: &OptionDef, values: &[String]) -> Result<(), ParseError> {
+        if let Some(expected_type) = &o.expected_type {
+            for v in values {
+                if let Err(message) = expected_type.validate(v) {
+                    return Err(ParseError::ConversionFailed {
+                        option: o.name.clone(),
+                        value: v.clone(),
+                        message,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_choices(o: &OptionDef, values: &[String]) -> Result<(), ParseError> {
+        if let Some(choices) = &o.choices {
+            for v in values {
+                if !choices.contains(v) {
+                    return Err(ParseError::InvalidChoice {
+                        option: o.name.clone(),
+                        value: v.clone(),
+                        choices: choices.clone(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolves the value to use for `o` when it's absent from the
+    /// command line: its backing environment variable first, then its
+    /// hard-coded default.
+    fn resolve_fallback_value(o: &OptionDef) -> Option<(String, ValueSource)> {
+        if let Some(env_name) = &o.env {
+            if let Ok(v) = env::var(env_name) {
+                return Some((v, ValueSource::Environment));
+            }
+        }
+        o.default.clone().map(|v| (v, ValueSource::Default))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
+    use std::sync::Mutex;
+
+    // `std::env::set_var`/`remove_var` mutate process-global state, which
+    // the stdlib does not guarantee is safe across `cargo test`'s
+    // concurrent test threads. Every test that touches an environment
+    // variable must hold this for its duration, even though each also
+    // uses a uniquely-named variable.
+    static ENV_VAR_LOCK: Mutex<()> = Mutex::new(());
 
     fn get_program_name() -> String {
         env::current_exe()
@@ -338,10 +930,10 @@ mod tests {
     fn test_single_option() {
         let mut parser = OptionParser::new();
         let key = "input".to_string();
-        parser.add_option(&key, "", None, None, None, None, None);
+        parser.add_option(&key, "", None, None, None, None, None, None, None, None);
 
         let options = setup_user_input_option(vec!["--input", "INPUT_VALUE"]);
-        let args = parser.parse_with_args(options);
+        let args = parser.try_parse(options).unwrap();
 
         assert_eq!(args.defined_len(), 1);
         assert_eq!(args.parsed_len(), 1);
@@ -350,7 +942,7 @@ mod tests {
         assert_eq!(args.get(&key), Some(&expected_value));
         assert_eq!(
             format!("{:?}", args),
-            "Options { defined_names: [\"input\"], parsed_options: {\"input\": [\"INPUT_VALUE\"]} }",
+            "Options { defined_names: [\"input\"], parsed_options: {\"input\": [\"INPUT_VALUE\"]}, positional_options: {}, free: [], value_sources: {\"input\": CommandLine} }",
         );
     }
 
@@ -359,10 +951,12 @@ mod tests {
         let mut parser = OptionParser::new();
         let key = "input".to_string();
         let short_key = "i".to_string();
-        parser.add_option(&key, &short_key, None, None, None, None, None);
+        parser.add_option(
+            &key, &short_key, None, None, None, None, None, None, None, None,
+        );
 
         let options = setup_user_input_option(vec!["-i", "INPUT_VALUE"]);
-        let args = parser.parse_with_args(options);
+        let args = parser.try_parse(options).unwrap();
 
         assert_eq!(args.defined_len(), 1);
         assert_eq!(args.parsed_len(), 1);
@@ -371,7 +965,7 @@ mod tests {
         assert_eq!(args.get(&key), Some(&expected_value));
         assert_eq!(
             format!("{:?}", args),
-            "Options { defined_names: [\"input\"], parsed_options: {\"input\": [\"INPUT_VALUE\"]} }",
+            "Options { defined_names: [\"input\"], parsed_options: {\"input\": [\"INPUT_VALUE\"]}, positional_options: {}, free: [], value_sources: {\"input\": CommandLine} }",
         );
     }
 
@@ -379,10 +973,21 @@ mod tests {
     fn test_single_flag_option() {
         let mut parser = OptionParser::new();
         let key = "verbose".to_string();
-        parser.add_option(&key, "", None, None, None, Some(OptAction::StoreTrue), None);
+        parser.add_option(
+            &key,
+            "",
+            None,
+            None,
+            None,
+            Some(OptAction::StoreTrue),
+            None,
+            None,
+            None,
+            None,
+        );
 
         let options = setup_user_input_option(vec!["--verbose"]);
-        let args = parser.parse_with_args(options);
+        let args = parser.try_parse(options).unwrap();
 
         assert_eq!(args.defined_len(), 1);
         assert_eq!(args.parsed_len(), 1);
@@ -391,7 +996,7 @@ mod tests {
         assert_eq!(args.get(&key), Some(&expected_value));
         assert_eq!(
             format!("{:?}", args),
-            "Options { defined_names: [\"verbose\"], parsed_options: {\"verbose\": [\"true\"]} }",
+            "Options { defined_names: [\"verbose\"], parsed_options: {\"verbose\": [\"true\"]}, positional_options: {}, free: [], value_sources: {\"verbose\": CommandLine} }",
         );
     }
 
@@ -399,7 +1004,7 @@ mod tests {
     fn test_single_option_without_user_given_values() {
         let mut parser = OptionParser::new();
         let key = "input".to_string();
-        parser.add_option(&key, "", None, None, None, None, None);
+        parser.add_option(&key, "", None, None, None, None, None, None, None, None);
         let args = parser.parse();
 
         assert_eq!(args.defined_len(), 1);
@@ -408,7 +1013,526 @@ mod tests {
         assert_eq!(args.get(&key), None);
         assert_eq!(
             format!("{:?}", args),
-            "Options { defined_names: [\"input\"], parsed_options: {} }",
+            "Options { defined_names: [\"input\"], parsed_options: {}, positional_options: {}, free: [], value_sources: {} }",
+        );
+    }
+
+    #[test]
+    fn test_get_as_converts_value() {
+        let mut parser = OptionParser::new();
+        let key = "count".to_string();
+        parser.add_option(&key, "", None, None, None, None, None, None, None, None);
+
+        let options = setup_user_input_option(vec!["--count", "42"]);
+        let args = parser.try_parse(options).unwrap();
+
+        assert_eq!(args.get_as::<i32>(&key), Ok(Some(42)));
+    }
+
+    #[test]
+    fn test_get_as_missing_value_returns_none() {
+        let mut parser = OptionParser::new();
+        let key = "count".to_string();
+        parser.add_option(&key, "", None, None, None, None, None, None, None, None);
+        let args = parser.parse();
+
+        assert_eq!(args.get_as::<i32>(&key), Ok(None));
+    }
+
+    #[test]
+    fn test_get_as_conversion_error() {
+        let mut parser = OptionParser::new();
+        let key = "count".to_string();
+        parser.add_option(&key, "", None, None, None, None, None, None, None, None);
+
+        let options = setup_user_input_option(vec!["--count", "not-a-number"]);
+        let args = parser.try_parse(options).unwrap();
+
+        assert_eq!(
+            args.get_as::<i32>(&key),
+            Err(ParseError::ConversionFailed {
+                option: key.clone(),
+                value: "not-a-number".to_string(),
+                message: "invalid digit found in string".to_string(),
+            }),
+        );
+    }
+
+    #[test]
+    fn test_get_all_as_converts_values() {
+        let mut parser = OptionParser::new();
+        let key = "count".to_string();
+        parser.add_option(
+            &key,
+            "",
+            None,
+            Some(true),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+
+        let options = setup_user_input_option(vec!["--count", "1", "--count", "2"]);
+        let args = parser.try_parse(options).unwrap();
+
+        assert_eq!(args.get_all_as::<i32>(&key), Ok(Some(vec![1, 2])));
+    }
+
+    #[test]
+    fn test_add_option_expected_type_validated_at_parse_time() {
+        let mut parser = OptionParser::new();
+        let key = "count".to_string();
+        parser.add_option(
+            &key,
+            "",
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(ValueType::Int),
+            None,
+            None,
+        );
+
+        let options = setup_user_input_option(vec!["--count", "nope"]);
+        assert_eq!(
+            parser.try_parse(options).unwrap_err(),
+            ParseError::ConversionFailed {
+                option: key.clone(),
+                value: "nope".to_string(),
+                message: "invalid digit found in string".to_string(),
+            },
+        );
+    }
+
+    #[test]
+    fn test_positional_argument_is_bound() {
+        let mut parser = OptionParser::new();
+        parser.add_argument("input", Some(true), None, None);
+
+        let options = setup_user_input_option(vec!["in.txt"]);
+        let args = parser.try_parse(options).unwrap();
+
+        let expected_value = vec!["in.txt".to_string()];
+        assert_eq!(args.positional(&"input".to_string()), Some(&expected_value));
+        assert_eq!(args.free(), &expected_value);
+    }
+
+    #[test]
+    fn test_positional_argument_multiple_absorbs_remainder() {
+        let mut parser = OptionParser::new();
+        parser.add_argument("input", Some(true), Some(true), None);
+
+        let options = setup_user_input_option(vec!["a.txt", "b.txt", "c.txt"]);
+        let args = parser.try_parse(options).unwrap();
+
+        let expected_value = vec![
+            "a.txt".to_string(),
+            "b.txt".to_string(),
+            "c.txt".to_string(),
+        ];
+        assert_eq!(args.positional(&"input".to_string()), Some(&expected_value));
+    }
+
+    #[test]
+    fn test_positional_argument_required_but_missing() {
+        let mut parser = OptionParser::new();
+        parser.add_argument("input", Some(true), None, None);
+
+        let options = setup_user_input_option(vec![]);
+        assert_eq!(
+            parser.try_parse(options).unwrap_err(),
+            ParseError::MissingRequired("input".to_string()),
+        );
+    }
+
+    #[test]
+    fn test_positional_argument_too_many_given() {
+        let mut parser = OptionParser::new();
+        parser.add_argument("input", Some(true), None, None);
+
+        let options = setup_user_input_option(vec!["a.txt", "b.txt"]);
+        assert_eq!(
+            parser.try_parse(options).unwrap_err(),
+            ParseError::UnexpectedPositionalArgument(vec!["b.txt".to_string()]),
+        );
+    }
+
+    #[test]
+    fn test_try_parse_help_flag_returns_help_requested() {
+        let mut parser = OptionParser::new();
+        let key = "input".to_string();
+        parser.add_option(&key, "", None, None, None, None, None, None, None, None);
+
+        let options = setup_user_input_option(vec!["--help"]);
+        assert_eq!(
+            parser.try_parse(options).unwrap_err(),
+            ParseError::HelpRequested
+        );
+    }
+
+    #[test]
+    fn test_try_parse_unknown_option_is_getopts_failure() {
+        let mut parser = OptionParser::new();
+        let options = setup_user_input_option(vec!["--does-not-exist"]);
+        assert!(matches!(
+            parser.try_parse(options),
+            Err(ParseError::GetoptsFailure(_))
+        ));
+    }
+
+    #[test]
+    fn test_store_count_counts_occurrences() {
+        let mut parser = OptionParser::new();
+        let key = "verbose".to_string();
+        let short_key = "v".to_string();
+        parser.add_option(
+            &key,
+            &short_key,
+            None,
+            None,
+            None,
+            Some(OptAction::StoreCount),
+            None,
+            None,
+            None,
+            None,
+        );
+
+        let options = setup_user_input_option(vec!["-vvv"]);
+        let args = parser.try_parse(options).unwrap();
+
+        assert_eq!(args.get_as::<u32>(&key), Ok(Some(3)));
+    }
+
+    #[test]
+    fn test_store_count_defaults_to_not_present_when_unset() {
+        let mut parser = OptionParser::new();
+        let key = "verbose".to_string();
+        parser.add_option(
+            &key,
+            "",
+            None,
+            None,
+            None,
+            Some(OptAction::StoreCount),
+            None,
+            None,
+            None,
+            None,
+        );
+
+        let options = setup_user_input_option(vec![]);
+        let args = parser.try_parse(options).unwrap();
+
+        assert_eq!(args.get(&key), None);
+    }
+
+    #[test]
+    fn test_choices_accepts_allowed_value() {
+        let mut parser = OptionParser::new();
+        let key = "mode".to_string();
+        parser.add_option(
+            &key,
+            "",
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(vec!["fast".to_string(), "slow".to_string()]),
+            None,
+        );
+
+        let options = setup_user_input_option(vec!["--mode", "fast"]);
+        let args = parser.try_parse(options).unwrap();
+
+        assert_eq!(args.get(&key), Some(&vec!["fast".to_string()]));
+    }
+
+    #[test]
+    fn test_choices_rejects_disallowed_value() {
+        let mut parser = OptionParser::new();
+        let key = "mode".to_string();
+        parser.add_option(
+            &key,
+            "",
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(vec!["fast".to_string(), "slow".to_string()]),
+            None,
+        );
+
+        let options = setup_user_input_option(vec!["--mode", "turbo"]);
+
+        assert_eq!(
+            parser.try_parse(options).unwrap_err(),
+            ParseError::InvalidChoice {
+                option: key,
+                value: "turbo".to_string(),
+                choices: vec!["fast".to_string(), "slow".to_string()],
+            },
+        );
+    }
+
+    #[test]
+    fn test_wrap_text_breaks_on_width() {
+        let wrapped = OptionParser::wrap_text("the quick brown fox jumps", 10);
+        assert_eq!(
+            wrapped,
+            vec![
+                "the quick".to_string(),
+                "brown fox".to_string(),
+                "jumps".to_string(),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_format_help_row_aligns_short_name_on_one_line() {
+        let row = OptionParser::format_help_row("-i, --input VALUE", "sets the input", 80);
+        assert_eq!(row, "    -i, --input VALUE   sets the input");
+    }
+
+    #[test]
+    fn test_format_help_row_wraps_long_name_to_next_line() {
+        let row = OptionParser::format_help_row(
+            "--some-very-long-option-name VALUE",
+            "sets the input",
+            80,
+        );
+        assert_eq!(
+            row,
+            "    --some-very-long-option-name VALUE\n                        sets the input",
+        );
+    }
+
+    #[test]
+    fn test_env_fallback_used_when_option_absent() {
+        let _guard = ENV_VAR_LOCK.lock().unwrap();
+
+        let mut parser = OptionParser::new();
+        let key = "region".to_string();
+        let env_name = "GETOPTS_UTIL_TEST_REGION";
+        env::set_var(env_name, "us-east-1");
+
+        parser.add_option(
+            &key,
+            "",
+            None,
+            None,
+            Some("us-west-2"),
+            None,
+            None,
+            None,
+            None,
+            Some(env_name),
+        );
+
+        let options = setup_user_input_option(vec![]);
+        let args = parser.try_parse(options).unwrap();
+
+        env::remove_var(env_name);
+
+        assert_eq!(args.get(&key), Some(&vec!["us-east-1".to_string()]));
+        assert_eq!(args.value_source(&key), Some(&ValueSource::Environment));
+    }
+
+    #[test]
+    fn test_command_line_value_takes_precedence_over_env() {
+        let _guard = ENV_VAR_LOCK.lock().unwrap();
+
+        let mut parser = OptionParser::new();
+        let key = "region".to_string();
+        let env_name = "GETOPTS_UTIL_TEST_REGION_CLI";
+        env::set_var(env_name, "us-east-1");
+
+        parser.add_option(
+            &key,
+            "",
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(env_name),
+        );
+
+        let options = setup_user_input_option(vec!["--region", "eu-west-1"]);
+        let args = parser.try_parse(options).unwrap();
+
+        env::remove_var(env_name);
+
+        assert_eq!(args.get(&key), Some(&vec!["eu-west-1".to_string()]));
+        assert_eq!(args.value_source(&key), Some(&ValueSource::CommandLine));
+    }
+
+    #[test]
+    fn test_default_used_when_neither_command_line_nor_env_given() {
+        let _guard = ENV_VAR_LOCK.lock().unwrap();
+
+        let mut parser = OptionParser::new();
+        let key = "region".to_string();
+        parser.add_option(
+            &key,
+            "",
+            None,
+            None,
+            Some("us-west-2"),
+            None,
+            None,
+            None,
+            None,
+            Some("GETOPTS_UTIL_TEST_REGION_UNSET"),
+        );
+
+        let options = setup_user_input_option(vec![]);
+        let args = parser.try_parse(options).unwrap();
+
+        assert_eq!(args.get(&key), Some(&vec!["us-west-2".to_string()]));
+        assert_eq!(args.value_source(&key), Some(&ValueSource::Default));
+    }
+
+    #[test]
+    fn test_required_option_is_satisfied_by_env_when_absent_from_cli() {
+        let _guard = ENV_VAR_LOCK.lock().unwrap();
+
+        let mut parser = OptionParser::new();
+        let key = "api_key".to_string();
+        let env_name = "GETOPTS_UTIL_TEST_REQUIRED_API_KEY";
+        env::set_var(env_name, "secret-value");
+
+        parser.add_option(
+            &key,
+            "",
+            Some(true),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(env_name),
+        );
+
+        let options = setup_user_input_option(vec![]);
+        let args = parser.try_parse(options).unwrap();
+
+        env::remove_var(env_name);
+
+        assert_eq!(args.get(&key), Some(&vec!["secret-value".to_string()]));
+        assert_eq!(args.value_source(&key), Some(&ValueSource::Environment));
+    }
+
+    #[test]
+    fn test_required_option_errors_when_env_and_cli_both_absent() {
+        let _guard = ENV_VAR_LOCK.lock().unwrap();
+
+        let mut parser = OptionParser::new();
+        let key = "api_key".to_string();
+        parser.add_option(
+            &key,
+            "",
+            Some(true),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some("GETOPTS_UTIL_TEST_REQUIRED_API_KEY_UNSET"),
+        );
+
+        let options = setup_user_input_option(vec![]);
+
+        assert_eq!(
+            parser.try_parse(options).unwrap_err(),
+            ParseError::MissingRequired(key),
+        );
+    }
+
+    #[test]
+    fn test_env_fallback_value_is_validated_against_choices() {
+        let _guard = ENV_VAR_LOCK.lock().unwrap();
+
+        let mut parser = OptionParser::new();
+        let key = "mode".to_string();
+        let env_name = "GETOPTS_UTIL_TEST_MODE_CHOICES";
+        env::set_var(env_name, "turbo");
+
+        parser.add_option(
+            &key,
+            "",
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(vec!["fast".to_string(), "slow".to_string()]),
+            Some(env_name),
+        );
+
+        let options = setup_user_input_option(vec![]);
+        let result = parser.try_parse(options);
+
+        env::remove_var(env_name);
+
+        assert_eq!(
+            result.unwrap_err(),
+            ParseError::InvalidChoice {
+                option: key,
+                value: "turbo".to_string(),
+                choices: vec!["fast".to_string(), "slow".to_string()],
+            },
+        );
+    }
+
+    #[test]
+    fn test_env_fallback_value_is_validated_against_expected_type() {
+        let _guard = ENV_VAR_LOCK.lock().unwrap();
+
+        let mut parser = OptionParser::new();
+        let key = "count".to_string();
+        let env_name = "GETOPTS_UTIL_TEST_COUNT_TYPE";
+        env::set_var(env_name, "nope");
+
+        parser.add_option(
+            &key,
+            "",
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(ValueType::Int),
+            None,
+            Some(env_name),
+        );
+
+        let options = setup_user_input_option(vec![]);
+        let result = parser.try_parse(options);
+
+        env::remove_var(env_name);
+
+        assert_eq!(
+            result.unwrap_err(),
+            ParseError::ConversionFailed {
+                option: key,
+                value: "nope".to_string(),
+                message: "invalid digit found in string".to_string(),
+            },
         );
     }
 }